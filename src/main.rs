@@ -10,14 +10,14 @@ use ggez::glam::*;
 use ggez::graphics;
 use ggez::graphics::Color;
 use ggez::graphics::Rect;
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use ggez::input::mouse::MouseButton;
 use ggez::{Context, GameResult};
 use std::env;
-use std::f32;
+use std::io::Read;
 use std::path;
 use wgpu::util::DeviceExt;
 
-type Isometry3 = Mat4;
-type Point3 = Vec3;
 type Vector3 = Vec3;
 
 struct Camera {
@@ -50,14 +50,96 @@ impl Default for Camera {
 
 impl Camera {
     fn build_view_projection_matrix(&self) -> Mat4 {
-        // 1.
-        let view = default_view();
-        // 2.
-        let proj = Mat4::perspective_rh(f32::consts::PI / 4.0, 4.0 / 3.0, 1.0, 10.0);
-
-        // 3.
-        return proj * view;
-        // OPENGL_TO_WGPU_MATRIX * proj * view;
+        let view = Mat4::look_at_rh(self.eye, self.target, self.up);
+        // The pipeline uses a reverse-Z depth buffer (near = 1.0, far = 0.0),
+        // which `perspective_rh` gives us for free if near/far are swapped.
+        let proj = Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.zfar, self.znear);
+
+        proj * view
+    }
+}
+
+/// Moves the `Camera`'s eye/target in response to keyboard and mouse input:
+/// WASD pans forward/strafe along the view vector, and dragging the left
+/// mouse button orbits the eye around the target.
+struct CameraController {
+    speed: f32,
+    sensitivity: f32,
+    move_forward: bool,
+    move_backward: bool,
+    strafe_left: bool,
+    strafe_right: bool,
+    dragging: bool,
+    yaw: f32,
+    pitch: f32,
+    distance: f32,
+}
+
+impl CameraController {
+    fn new(speed: f32, sensitivity: f32, camera: &Camera) -> Self {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length();
+        let yaw = offset.z.atan2(offset.x);
+        let pitch = (offset.y / distance).asin();
+
+        Self {
+            speed,
+            sensitivity,
+            move_forward: false,
+            move_backward: false,
+            strafe_left: false,
+            strafe_right: false,
+            dragging: false,
+            yaw,
+            pitch,
+            distance,
+        }
+    }
+
+    fn process_key(&mut self, keycode: KeyCode, pressed: bool) {
+        match keycode {
+            KeyCode::W => self.move_forward = pressed,
+            KeyCode::S => self.move_backward = pressed,
+            KeyCode::A => self.strafe_left = pressed,
+            KeyCode::D => self.strafe_right = pressed,
+            _ => {}
+        }
+    }
+
+    fn process_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        if button == MouseButton::Left {
+            self.dragging = pressed;
+        }
+    }
+
+    fn process_mouse_motion(&mut self, dx: f32, dy: f32) {
+        if !self.dragging {
+            return;
+        }
+        self.yaw += dx * self.sensitivity;
+        self.pitch = (self.pitch - dy * self.sensitivity).clamp(-1.5, 1.5);
+    }
+
+    fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        let motion = (self.move_forward as i32 - self.move_backward as i32) as f32 * forward
+            + (self.strafe_right as i32 - self.strafe_left as i32) as f32 * right;
+
+        if motion != Vec3::ZERO {
+            let motion = motion.normalize() * self.speed * dt;
+            camera.eye += motion;
+            camera.target += motion;
+        }
+
+        if self.dragging {
+            let offset = Vec3::new(
+                self.distance * self.pitch.cos() * self.yaw.cos(),
+                self.distance * self.pitch.sin(),
+                self.distance * self.pitch.cos() * self.yaw.sin(),
+            );
+            camera.eye = camera.target + offset;
+        }
     }
 }
 
@@ -65,6 +147,9 @@ impl Camera {
 // This is so we can store this in a buffer
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
+    // Used for the specular lighting term; a vec3 would leave this
+    // un-16-byte-aligned for the following matrix, so pad to a vec4.
+    view_position: [f32; 4],
     // We can't use cgmath with bytemuck directly so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     view_proj: [[f32; 4]; 4],
@@ -73,6 +158,7 @@ struct CameraUniform {
 impl CameraUniform {
     fn new() -> Self {
         Self {
+            view_position: [0.0; 4],
             view_proj: [
                 Mat4::IDENTITY.x_axis.into(),
                 Mat4::IDENTITY.y_axis.into(),
@@ -83,6 +169,7 @@ impl CameraUniform {
     }
 
     fn update_view_proj(&mut self, camera: &Camera) {
+        self.view_position = camera.eye.extend(1.0).into();
         let view = camera.build_view_projection_matrix();
         self.view_proj = [
             view.x_axis.into(),
@@ -93,44 +180,274 @@ impl CameraUniform {
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    position: [f32; 3],
+    // Uniforms require 16 byte spacing, so we pad out the struct to that.
+    _pad: u32,
+    color: [f32; 3],
+    _pad2: u32,
+}
+
 #[derive(Clone, Copy, bytemuck::Zeroable, bytemuck::Pod)]
 #[repr(C)]
 struct Vertex {
     pos: [f32; 4],
     tex_coord: [f32; 2],
+    normal: [f32; 3],
+}
+
+/// One cube to draw: a world-space position and rotation, packed down
+/// into a model matrix for the instance buffer via `to_raw`.
+struct Instance {
+    position: Vec3,
+    rotation: Quat,
 }
 
-impl Vertex {
-    fn new(p: [i8; 3], t: [i8; 2]) -> Vertex {
-        Vertex {
-            pos: [f32::from(p[0]), f32::from(p[1]), f32::from(p[2]), 1.0],
-            tex_coord: [f32::from(t[0]), f32::from(t[1])],
+impl Instance {
+    fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw {
+            model: (Mat4::from_translation(self.position) * Mat4::from_quat(self.rotation))
+                .to_cols_array_2d(),
         }
     }
 }
 
-fn default_view() -> Isometry3 {
-    // Eye location, target location, up-vector
-    Mat4::look_at_rh(
-        Point3::new(1.5f32, -5.0, 3.0),
-        Point3::new(0f32, 0.0, 0.0),
-        Vector3::Z,
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceRaw {
+    model: [[f32; 4]; 4],
+}
+
+const NUM_INSTANCES_PER_ROW: u32 = 10;
+
+/// One drawable piece of a loaded model: its own vertex/index buffers
+/// (a multi-mesh `.obj` yields one of these per sub-mesh) and the bind
+/// group for whichever material's texture it uses.
+struct Mesh {
+    verts: wgpu::Buffer,
+    inds: wgpu::Buffer,
+    num_indices: u32,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A sub-mesh as parsed from an `.obj`, before its texture has been
+/// turned into a bind group (that needs the pipeline's bind group layout,
+/// which isn't built yet when the model is loaded).
+struct LoadedMesh {
+    verts: wgpu::Buffer,
+    inds: wgpu::Buffer,
+    num_indices: u32,
+    diffuse_texture: Option<path::PathBuf>,
+}
+
+/// Parses a (possibly multi-mesh) `.obj` + `.mtl` pair from the ggez
+/// resource directory into vertex/index buffers using the existing
+/// `Vertex` layout, one `LoadedMesh` per sub-mesh.
+fn load_model(ctx: &mut Context, obj_path: &path::Path) -> GameResult<Vec<LoadedMesh>> {
+    let parent = obj_path.parent().unwrap_or_else(|| path::Path::new("/"));
+
+    let mut obj_reader = std::io::BufReader::new(ctx.fs.open(obj_path)?);
+    let (models, materials) = tobj::load_obj_buf(
+        &mut obj_reader,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+        |mtl_path| {
+            let mtl_file = ctx
+                .fs
+                .open(parent.join(mtl_path))
+                .map_err(|_| tobj::LoadError::OpenFileFailed)?;
+            tobj::load_mtl_buf(&mut std::io::BufReader::new(mtl_file))
+        },
     )
+    .map_err(|e| ggez::GameError::ResourceLoadError(e.to_string()))?;
+    let materials = materials.map_err(|e| ggez::GameError::ResourceLoadError(e.to_string()))?;
+
+    let meshes = models
+        .into_iter()
+        .map(|model| {
+            let mesh = model.mesh;
+            let vertex_count = mesh.positions.len() / 3;
+            let vertex_data: Vec<Vertex> = (0..vertex_count)
+                .map(|i| {
+                    let tex_coord = if mesh.texcoords.is_empty() {
+                        [0.0, 0.0]
+                    } else {
+                        [mesh.texcoords[i * 2], 1.0 - mesh.texcoords[i * 2 + 1]]
+                    };
+                    let normal = if mesh.normals.is_empty() {
+                        [0.0, 0.0, 1.0]
+                    } else {
+                        [
+                            mesh.normals[i * 3],
+                            mesh.normals[i * 3 + 1],
+                            mesh.normals[i * 3 + 2],
+                        ]
+                    };
+                    Vertex {
+                        pos: [
+                            mesh.positions[i * 3],
+                            mesh.positions[i * 3 + 1],
+                            mesh.positions[i * 3 + 2],
+                            1.0,
+                        ],
+                        tex_coord,
+                        normal,
+                    }
+                })
+                .collect();
+
+            let verts = ctx
+                .gfx
+                .wgpu()
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Model Vertex Buffer"),
+                    contents: bytemuck::cast_slice(&vertex_data),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+            let inds = ctx
+                .gfx
+                .wgpu()
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Model Index Buffer"),
+                    contents: bytemuck::cast_slice(&mesh.indices),
+                    usage: wgpu::BufferUsages::INDEX,
+                });
+
+            let diffuse_texture = mesh
+                .material_id
+                .and_then(|id| materials.get(id))
+                .filter(|material| !material.diffuse_texture.is_empty())
+                .map(|material| parent.join(&material.diffuse_texture));
+
+            LoadedMesh {
+                verts,
+                inds,
+                num_indices: mesh.indices.len() as u32,
+                diffuse_texture,
+            }
+        })
+        .collect();
+
+    Ok(meshes)
+}
+
+/// Loads a PNG/JPEG from the ggez resource directory into a trilinear-filtered
+/// `wgpu::Texture` with a full mipmap chain, downsampling on the CPU one level
+/// at a time so distant faces stay sharp without shimmering.
+fn load_texture(
+    ctx: &mut Context,
+    path: &path::Path,
+) -> GameResult<(wgpu::Texture, wgpu::TextureView, wgpu::Sampler)> {
+    let mut bytes = Vec::new();
+    ctx.fs
+        .open(path)?
+        .read_to_end(&mut bytes)
+        .map_err(|e| ggez::GameError::ResourceLoadError(format!("{}: {e}", path.display())))?;
+    let image = image::load_from_memory(&bytes)
+        .map_err(|e| ggez::GameError::ResourceLoadError(format!("{}: {e}", path.display())))?;
+
+    let (width, height) = (image.width(), image.height());
+    let mip_level_count = (width.max(height) as f32).log2().floor() as u32 + 1;
+
+    let texture = ctx
+        .gfx
+        .wgpu()
+        .device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: path.to_str(),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+    let mut level_image = image;
+    let mut level_width = width;
+    let mut level_height = height;
+    for mip_level in 0..mip_level_count {
+        if mip_level > 0 {
+            level_width = (level_width / 2).max(1);
+            level_height = (level_height / 2).max(1);
+            level_image = level_image.resize_exact(
+                level_width,
+                level_height,
+                image::imageops::FilterType::Triangle,
+            );
+        }
+
+        let rgba = level_image.to_rgba8();
+        ctx.gfx.wgpu().queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * level_width),
+                rows_per_image: Some(level_height),
+            },
+            wgpu::Extent3d {
+                width: level_width,
+                height: level_height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = ctx
+        .gfx
+        .wgpu()
+        .device
+        .create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+    Ok((texture, view, sampler))
 }
 
 struct MainState {
     frames: usize,
     camera: Camera,
+    camera_controller: CameraController,
     screen_coords: Rect,
 
-    verts: wgpu::Buffer,
-    inds: wgpu::Buffer,
+    meshes: Vec<Mesh>,
+    instances: wgpu::Buffer,
+    num_instances: u32,
     pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
     depth: graphics::ScreenImage,
     camera_uniform: CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
+    light_uniform: LightUniform,
+    light_buffer: wgpu::Buffer,
+    light_bind_group: wgpu::BindGroup,
+    depth_debug_enabled: bool,
+    depth_debug_pipeline: wgpu::RenderPipeline,
+    depth_debug_bind_group_layout: wgpu::BindGroupLayout,
+    depth_debug_sampler: wgpu::Sampler,
+    depth_debug_uniform: wgpu::Buffer,
 }
 
 impl MainState {
@@ -142,71 +459,36 @@ impl MainState {
             .device
             .create_shader_module(wgpu::include_wgsl!("../resources/cube.wgsl"));
 
-        // Cube geometry
-        let vertex_data = [
-            // top (0, 0, 1)
-            Vertex::new([-1, -1, 1], [0, 0]),
-            Vertex::new([1, -1, 1], [1, 0]),
-            Vertex::new([1, 1, 1], [1, 1]),
-            Vertex::new([-1, 1, 1], [0, 1]),
-            // bottom (0, 0, -1)
-            Vertex::new([-1, 1, -1], [1, 0]),
-            Vertex::new([1, 1, -1], [0, 0]),
-            Vertex::new([1, -1, -1], [0, 1]),
-            Vertex::new([-1, -1, -1], [1, 1]),
-            // right (1, 0, 0)
-            Vertex::new([1, -1, -1], [0, 0]),
-            Vertex::new([1, 1, -1], [1, 0]),
-            Vertex::new([1, 1, 1], [1, 1]),
-            Vertex::new([1, -1, 1], [0, 1]),
-            // left (-1, 0, 0)
-            Vertex::new([-1, -1, 1], [1, 0]),
-            Vertex::new([-1, 1, 1], [0, 0]),
-            Vertex::new([-1, 1, -1], [0, 1]),
-            Vertex::new([-1, -1, -1], [1, 1]),
-            // front (0, 1, 0)
-            Vertex::new([1, 1, -1], [1, 0]),
-            Vertex::new([-1, 1, -1], [0, 0]),
-            Vertex::new([-1, 1, 1], [0, 1]),
-            Vertex::new([1, 1, 1], [1, 1]),
-            // back (0, -1, 0)
-            Vertex::new([1, -1, 1], [0, 0]),
-            Vertex::new([-1, -1, 1], [1, 0]),
-            Vertex::new([-1, -1, -1], [1, 1]),
-            Vertex::new([1, -1, -1], [0, 1]),
-        ];
-
-        #[rustfmt::skip]
-        let index_data: &[u32] = &[
-             0,  1,  2,  2,  3,  0, // top
-             4,  5,  6,  6,  7,  4, // bottom
-             8,  9, 10, 10, 11,  8, // right
-            12, 13, 14, 14, 15, 12, // left
-            16, 17, 18, 18, 19, 16, // front
-            20, 21, 22, 22, 23, 20, // back
-        ];
+        // Model geometry, loaded at runtime instead of baked into the binary.
+        let loaded_meshes = load_model(ctx, path::Path::new("/cube.obj"))?;
 
-        // Create vertex and index buffers.
-        let verts = ctx
-            .gfx
-            .wgpu()
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(vertex_data.as_slice()),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-        let inds = ctx
-            .gfx
-            .wgpu()
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: None,
-                contents: bytemuck::cast_slice(index_data),
-                usage: wgpu::BufferUsages::INDEX,
-            });
+        // A NUM_INSTANCES_PER_ROW x NUM_INSTANCES_PER_ROW grid of cubes, centered on the origin.
+        let instance_data = (0..NUM_INSTANCES_PER_ROW)
+            .flat_map(|z| {
+                (0..NUM_INSTANCES_PER_ROW).map(move |x| {
+                    let position = Vec3::new(x as f32, 0.0, z as f32)
+                        - Vec3::new(NUM_INSTANCES_PER_ROW as f32 / 2.0, 0.0, NUM_INSTANCES_PER_ROW as f32 / 2.0);
+                    Instance {
+                        position: position * 2.5,
+                        rotation: Quat::IDENTITY,
+                    }
+                    .to_raw()
+                })
+            })
+            .collect::<Vec<_>>();
+        let num_instances = instance_data.len() as u32;
+        let instances =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Instance Buffer"),
+                    contents: bytemuck::cast_slice(&instance_data),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
 
         let camera = Camera::default();
+        let camera_controller = CameraController::new(3.0, 0.005, &camera);
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
@@ -226,7 +508,7 @@ impl MainState {
                 .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                     entries: &[wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
@@ -274,13 +556,62 @@ impl MainState {
                     label: Some("camera_bind_group"),
                 });
 
+        let light_uniform = LightUniform {
+            position: [4.0, 4.0, 4.0],
+            _pad: 0,
+            color: [1.0, 1.0, 1.0],
+            _pad2: 0,
+        };
+        let light_buffer =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Light Buffer"),
+                    contents: bytemuck::cast_slice(&[light_uniform]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+        let light_bind_group_layout =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    }],
+                    label: Some("light_bind_group_layout"),
+                });
+        let light_bind_group =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &light_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: light_buffer.as_entire_binding(),
+                    }],
+                    label: Some("light_bind_group"),
+                });
+
         let render_pipeline_layout =
             ctx.gfx
                 .wgpu()
                 .device
                 .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                     label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                    bind_group_layouts: &[
+                        &texture_bind_group_layout,
+                        &camera_bind_group_layout,
+                        &light_bind_group_layout,
+                    ],
                     push_constant_ranges: &[],
                 });
 
@@ -310,6 +641,39 @@ impl MainState {
                                     offset: 16,
                                     shader_location: 1,
                                 },
+                                // normal
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x3,
+                                    offset: 24,
+                                    shader_location: 2,
+                                },
+                            ],
+                        }, wgpu::VertexBufferLayout {
+                            array_stride: std::mem::size_of::<InstanceRaw>() as _,
+                            step_mode: wgpu::VertexStepMode::Instance,
+                            // A mat4 takes up 4 vertex attribute slots, since each slot
+                            // is limited to 4 floats, so the rows go in locations 5-8.
+                            attributes: &[
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: 0,
+                                    shader_location: 5,
+                                },
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: std::mem::size_of::<[f32; 4]>() as u64,
+                                    shader_location: 6,
+                                },
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: std::mem::size_of::<[f32; 4]>() as u64 * 2,
+                                    shader_location: 7,
+                                },
+                                wgpu::VertexAttribute {
+                                    format: wgpu::VertexFormat::Float32x4,
+                                    offset: std::mem::size_of::<[f32; 4]>() as u64 * 3,
+                                    shader_location: 8,
+                                },
                             ],
                         }],
                     },
@@ -346,48 +710,164 @@ impl MainState {
                     multiview: None,
                 });
 
-        // Create 1-pixel blue texture.
-        let image =
+        // Fallback texture for materials (or meshes) with no diffuse map.
+        let placeholder_image =
             graphics::Image::from_solid(ctx, 1, graphics::Color::from_rgb(0x20, 0xA0, 0xC0));
-
-        let sampler = ctx
+        let placeholder_sampler = ctx
             .gfx
             .wgpu()
             .device
             .create_sampler(&graphics::Sampler::default().into());
 
-        let bind_group = ctx
+        // Load each mesh's material texture from disk, with a full mipmap chain.
+        let meshes = loaded_meshes
+            .into_iter()
+            .map(|mesh| {
+                let (view, sampler) = match &mesh.diffuse_texture {
+                    Some(path) => {
+                        let (_texture, view, sampler) = load_texture(ctx, path)?;
+                        (view, sampler)
+                    }
+                    None => (
+                        placeholder_image.wgpu().1.clone(),
+                        placeholder_sampler.clone(),
+                    ),
+                };
+
+                let bind_group = ctx
+                    .gfx
+                    .wgpu()
+                    .device
+                    .create_bind_group(&wgpu::BindGroupDescriptor {
+                        label: None,
+                        layout: &pipeline.get_bind_group_layout(0),
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: wgpu::BindingResource::TextureView(&view),
+                            },
+                            wgpu::BindGroupEntry {
+                                binding: 1,
+                                resource: wgpu::BindingResource::Sampler(&sampler),
+                            },
+                        ],
+                    });
+
+                Ok(Mesh {
+                    verts: mesh.verts,
+                    inds: mesh.inds,
+                    num_indices: mesh.num_indices,
+                    bind_group,
+                })
+            })
+            .collect::<GameResult<Vec<_>>>()?;
+
+        let depth = graphics::ScreenImage::new(ctx, graphics::ImageFormat::Depth32Float, 1., 1., 1);
+
+        // Optional debug pass: renders the linearized depth buffer to a
+        // corner quad, toggled on with F1.
+        let depth_debug_shader = ctx
             .gfx
             .wgpu()
             .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &pipeline.get_bind_group_layout(0),
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(image.wgpu().1),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sampler),
+            .create_shader_module(wgpu::include_wgsl!("../resources/depth_debug.wgsl"));
+        let depth_debug_bind_group_layout =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Buffer {
+                                ty: wgpu::BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: None,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                multisampled: false,
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                sample_type: wgpu::TextureSampleType::Depth,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                            count: None,
+                        },
+                    ],
+                    label: Some("depth_debug_bind_group_layout"),
+                });
+        let depth_debug_pipeline_layout =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Depth Debug Pipeline Layout"),
+                    bind_group_layouts: &[&depth_debug_bind_group_layout],
+                    push_constant_ranges: &[],
+                });
+        let depth_debug_pipeline =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Depth Debug Pipeline"),
+                    layout: Some(&depth_debug_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &depth_debug_shader,
+                        entry_point: "vs_main",
+                        buffers: &[],
                     },
-                ],
-            });
-
-        let depth = graphics::ScreenImage::new(ctx, graphics::ImageFormat::Depth32Float, 1., 1., 1);
-
-        // FOV, spect ratio, znear, zfar
-        // let proj = Mat4::perspective_rh(f32::consts::PI / 4.0, 4.0 / 3.0, 1.0, 10.0);
-        // let transform = proj * default_view();
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &depth_debug_shader,
+                        entry_point: "fs_main",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: ctx.gfx.surface_format(),
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    multiview: None,
+                });
+        let depth_debug_sampler =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_sampler(&wgpu::SamplerDescriptor {
+                    mag_filter: wgpu::FilterMode::Nearest,
+                    min_filter: wgpu::FilterMode::Nearest,
+                    ..Default::default()
+                });
+        let depth_debug_uniform =
+            ctx.gfx
+                .wgpu()
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Depth Debug Uniform Buffer"),
+                    contents: bytemuck::cast_slice(&[camera.znear, camera.zfar]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
 
         Ok(MainState {
             frames: 0,
-            camera: Camera::default(),
-            verts,
-            inds,
+            camera,
+            camera_controller,
+            meshes,
+            instances,
+            num_instances,
             pipeline,
-            bind_group,
             depth,
             screen_coords: Rect {
                 x: 0.,
@@ -398,6 +878,14 @@ impl MainState {
             camera_uniform,
             camera_buffer,
             camera_bind_group,
+            light_uniform,
+            light_buffer,
+            light_bind_group,
+            depth_debug_enabled: false,
+            depth_debug_pipeline,
+            depth_debug_bind_group_layout,
+            depth_debug_sampler,
+            depth_debug_uniform,
         })
     }
 
@@ -412,14 +900,84 @@ impl MainState {
 }
 
 impl event::EventHandler<ggez::GameError> for MainState {
-    fn resize_event(&mut self, _ctx: &mut Context, width: f32, height: f32) -> GameResult {
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
         // println!("Resized screen to {}, {}", width, height);
         let new_rect = graphics::Rect::new(0.0, 0.0, width as f32, height as f32);
         self.screen_coords = new_rect;
+        self.camera.aspect = width / height;
+        self.depth = graphics::ScreenImage::new(ctx, graphics::ImageFormat::Depth32Float, 1., 1., 1);
+        Ok(())
+    }
+
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let dt = ctx.time.delta().as_secs_f32();
+        self.camera_controller.update_camera(&mut self.camera, dt);
+        self.camera_uniform.update_view_proj(&self.camera);
+        ctx.gfx.wgpu().queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+
+        let old_position = Vec3::from(self.light_uniform.position);
+        let new_position = Quat::from_axis_angle(Vec3::Y, dt) * old_position;
+        self.light_uniform.position = new_position.into();
+        ctx.gfx.wgpu().queue.write_buffer(
+            &self.light_buffer,
+            0,
+            bytemuck::cast_slice(&[self.light_uniform]),
+        );
+        Ok(())
+    }
+
+    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, repeated: bool) -> GameResult {
+        if let Some(keycode) = input.keycode {
+            if keycode == KeyCode::F1 && !repeated {
+                self.depth_debug_enabled = !self.depth_debug_enabled;
+            }
+            self.camera_controller.process_key(keycode, true);
+        }
+        Ok(())
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> GameResult {
+        if let Some(keycode) = input.keycode {
+            self.camera_controller.process_key(keycode, false);
+        }
         Ok(())
     }
 
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+    fn mouse_button_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> GameResult {
+        self.camera_controller.process_mouse_button(button, true);
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        button: MouseButton,
+        _x: f32,
+        _y: f32,
+    ) -> GameResult {
+        self.camera_controller.process_mouse_button(button, false);
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        _x: f32,
+        _y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> GameResult {
+        self.camera_controller.process_mouse_motion(dx, dy);
         Ok(())
     }
 
@@ -448,21 +1006,67 @@ impl event::EventHandler<ggez::GameError> for MainState {
                     view: depth.wgpu().1,
                     depth_ops: Some(wgpu::Operations {
                         load: wgpu::LoadOp::Clear(0.),
-                        store: false,
+                        store: true,
                     }),
                     stencil_ops: None,
                 }),
             });
 
             pass.set_pipeline(&self.pipeline);
-            pass.set_bind_group(0, &self.bind_group, &[]);
             // NEW!
             pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            // pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            // pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            pass.set_vertex_buffer(0, self.verts.slice(..));
-            pass.set_index_buffer(self.inds.slice(..), wgpu::IndexFormat::Uint32);
-            pass.draw_indexed(0..36, 0, 0..1);
+            pass.set_bind_group(2, &self.light_bind_group, &[]);
+            pass.set_vertex_buffer(1, self.instances.slice(..));
+            for mesh in &self.meshes {
+                pass.set_bind_group(0, &mesh.bind_group, &[]);
+                pass.set_vertex_buffer(0, mesh.verts.slice(..));
+                pass.set_index_buffer(mesh.inds.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..mesh.num_indices, 0, 0..self.num_instances);
+            }
+        }
+
+        if self.depth_debug_enabled {
+            let depth = self.depth.image(ctx);
+            let bind_group = ctx
+                .gfx
+                .wgpu()
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("depth_debug_bind_group"),
+                    layout: &self.depth_debug_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.depth_debug_uniform.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(depth.wgpu().1),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(&self.depth_debug_sampler),
+                        },
+                    ],
+                });
+
+            let frame = ctx.gfx.frame().clone();
+            let cmd = ctx.gfx.commands().unwrap();
+            let mut pass = cmd.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Debug Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: frame.wgpu().1,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.depth_debug_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..6, 0..1);
         }
 
         let mut canvas = graphics::Canvas::from_frame(ctx, None);